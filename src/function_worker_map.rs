@@ -1,5 +1,8 @@
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
 use std::vec::Vec;
+use tokio::sync::mpsc;
 
 type StaticName = [u8; 64];
 
@@ -11,12 +14,43 @@ fn static_name_from_str(s: &str) -> StaticName {
     name
 }
 
+// Mirrors the Active/Idle/Dead lifecycle Garage uses for its background
+// workers: `Active` while handling traffic, `Idle` when registered but
+// quiet, `Dead` once its heartbeat has expired and it must be excluded from
+// routing. `Draining` is operator-triggered: the worker keeps its in-flight
+// leases but is excluded from new selection until it's removed.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum WorkerState {
+    Active,
+    Idle,
+    Dead,
+    Draining,
+}
+
+fn now_ms() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_millis() as u64
+}
+
+// Backoff bounds for `report_failure`, mirroring Garage's resync-error model:
+// each consecutive failure doubles the wait, capped at `MAX_BACKOFF_MS`.
+const BASE_BACKOFF_MS: u64 = 1_000;
+const MAX_BACKOFF_MS: u64 = 60_000;
+
 struct FunctionWorkerConfig {
     function_name: StaticName, // Must be unique for a functionDefinition.
     worker_uuid: StaticName,   // Must be unique for a worker.
     timeout: u32,              // Hard-limit timeout in ms for the function to complete.
     traffic: u32,              // Ongoing functions exections on the Worker
     max_concurrency: u32,      // Maximum number of concurrent function execution on the worker.
+    state: WorkerState,
+    last_heartbeat_ms: u64,
+    error_count: u32,  // Consecutive failures since the last success.
+    last_error_at: u64,
+    next_try_at: u64, // Worker is quarantined from selection while now < next_try_at.
+    pending_removal: bool, // Set by remove_worker; actually removed once traffic reaches 0.
 }
 
 impl FunctionWorkerConfig {
@@ -27,8 +61,60 @@ impl FunctionWorkerConfig {
             timeout: self.timeout,
             traffic: self.traffic,
             max_concurrency: self.max_concurrency,
+            state: self.state,
+            last_heartbeat_ms: self.last_heartbeat_ms,
+            error_count: self.error_count,
+            last_error_at: self.last_error_at,
+            next_try_at: self.next_try_at,
+            pending_removal: self.pending_removal,
         }
     }
+
+    fn in_backoff(&self, now: u64) -> bool {
+        now < self.next_try_at
+    }
+
+    // Signed so it can never underflow when `traffic` temporarily exceeds
+    // `max_concurrency` (e.g. while a release is in flight).
+    fn available_capacity(&self) -> i64 {
+        self.max_concurrency as i64 - self.traffic as i64
+    }
+}
+
+// Snapshot of a worker's routing state, returned by `list_workers` so an
+// operator can see which workers are active, idle, or dead per function.
+pub struct WorkerInfo {
+    pub worker_uuid: StaticName,
+    pub state: WorkerState,
+    pub traffic: u32,
+    pub max_concurrency: u32,
+}
+
+// Per-function rollup returned by `metrics_snapshot`.
+pub struct FunctionMetrics {
+    pub function_name: StaticName,
+    pub worker_count: usize,
+    pub total_traffic: u32,
+    pub total_max_concurrency: u32,
+    pub available_capacity: i64,
+    pub saturated_count: usize,
+    pub dead_count: usize,
+    pub backing_off_count: usize,
+}
+
+// Per-worker gauge returned by `metrics_snapshot`, flat across all functions
+// so it can be fed straight into a Prometheus-style exporter.
+pub struct WorkerGauge {
+    pub function_name: StaticName,
+    pub worker_uuid: StaticName,
+    pub traffic: u32,
+    pub max_concurrency: u32,
+    pub state: WorkerState,
+}
+
+pub struct MetricsSnapshot {
+    pub functions: Vec<FunctionMetrics>,
+    pub workers: Vec<WorkerGauge>,
 }
 
 // FunctionWorkerConfig examples
@@ -59,19 +145,280 @@ impl FunctionWorkerConfig {
 // - FunctionName is the Key
 // - FunctionWorkerConfig Array is the Value
 
+// How many completed-call latencies the tranquilizer keeps per function to
+// base its admission pauses on.
+const TRANQUILIZER_WINDOW: usize = 20;
+
+// Adaptive admission smoothing for a single function, ported from Garage's
+// `Tranquilizer`. After every completed lease, `acquire_worker` is made to
+// pause proportionally to the mean duration of the last
+// `TRANQUILIZER_WINDOW` completed calls, trading latency for reduced
+// pressure on the worker. `tranquility == 0` disables the pause.
+struct Tranquilizer {
+    tranquility: u32,
+    recent_durations_ms: VecDeque<u64>,
+    ready_at_ms: u64,
+}
+
+impl Tranquilizer {
+    fn new() -> Tranquilizer {
+        Tranquilizer {
+            tranquility: 0,
+            recent_durations_ms: VecDeque::with_capacity(TRANQUILIZER_WINDOW),
+            ready_at_ms: 0,
+        }
+    }
+
+    fn record_completion(&mut self, now: u64, duration_ms: u64) {
+        if self.recent_durations_ms.len() == TRANQUILIZER_WINDOW {
+            self.recent_durations_ms.pop_front();
+        }
+        self.recent_durations_ms.push_back(duration_ms);
+
+        if self.tranquility > 0 {
+            let sum_ms: u64 = self.recent_durations_ms.iter().sum();
+            let mean_ms = sum_ms / self.recent_durations_ms.len() as u64;
+            let sleep_ms = mean_ms * self.tranquility as u64 / 100;
+            self.ready_at_ms = now + sleep_ms;
+        }
+    }
+}
+
+// Per-function worker storage. Workers are appended to `workers` and only
+// reordered by `remove_at`, which moves the last worker into the removed
+// slot; `capacity_index` buckets their indices by available capacity
+// so the least-busy lookup is a peek at the top bucket and a capacity change
+// is a single bucket move, instead of re-sorting the whole function on every
+// insert, acquire, or release.
+struct FunctionWorkerSlots {
+    workers: Vec<FunctionWorkerConfig>,
+    capacity_index: BTreeMap<i64, Vec<usize>>,
+    // Set by `pause_function`; while true, selection returns nothing for
+    // this function regardless of worker state, without disturbing
+    // in-flight leases.
+    paused: bool,
+}
+
+impl FunctionWorkerSlots {
+    fn new() -> FunctionWorkerSlots {
+        FunctionWorkerSlots {
+            workers: Vec::new(),
+            capacity_index: BTreeMap::new(),
+            paused: false,
+        }
+    }
+
+    fn push(&mut self, worker_config: FunctionWorkerConfig) {
+        let idx = self.workers.len();
+        let capacity = worker_config.available_capacity();
+        self.workers.push(worker_config);
+        self.capacity_index.entry(capacity).or_default().push(idx);
+    }
+
+    // Moves `idx` into the capacity bucket matching its current
+    // `available_capacity`. Call after mutating a worker's `traffic`.
+    fn reindex_capacity(&mut self, idx: usize, old_capacity: i64) {
+        let new_capacity = self.workers[idx].available_capacity();
+        if new_capacity == old_capacity {
+            return;
+        }
+        if let Some(bucket) = self.capacity_index.get_mut(&old_capacity) {
+            if let Some(pos) = bucket.iter().position(|&i| i == idx) {
+                bucket.swap_remove(pos);
+            }
+            if bucket.is_empty() {
+                self.capacity_index.remove(&old_capacity);
+            }
+        }
+        self.capacity_index.entry(new_capacity).or_default().push(idx);
+    }
+
+    // Drops `idx` out of `workers` entirely via swap_remove, fixing up the
+    // capacity index for both the removed slot and whichever slot got moved
+    // into its place. Only safe to call once the worker's traffic is zero.
+    fn remove_at(&mut self, idx: usize) {
+        let capacity = self.workers[idx].available_capacity();
+        if let Some(bucket) = self.capacity_index.get_mut(&capacity) {
+            if let Some(pos) = bucket.iter().position(|&i| i == idx) {
+                bucket.swap_remove(pos);
+            }
+            if bucket.is_empty() {
+                self.capacity_index.remove(&capacity);
+            }
+        }
+
+        let last = self.workers.len() - 1;
+        self.workers.swap_remove(idx);
+
+        if idx != last {
+            let moved_capacity = self.workers[idx].available_capacity();
+            if let Some(bucket) = self.capacity_index.get_mut(&moved_capacity) {
+                if let Some(pos) = bucket.iter().position(|&i| i == last) {
+                    bucket[pos] = idx;
+                }
+            }
+        }
+    }
+
+    // Highest-capacity worker that is not `Dead`/`Draining`, not in backoff,
+    // and still has spare capacity, plus whether any eligible worker was
+    // skipped for being in backoff (used to pick the right error when
+    // nothing is found). Always empty while the function is paused.
+    fn best_available(&self, now: u64) -> (Option<usize>, bool) {
+        let mut saw_backoff = false;
+        if self.paused {
+            return (None, false);
+        }
+        for (&capacity, bucket) in self.capacity_index.iter().rev() {
+            if capacity <= 0 {
+                break;
+            }
+            for &idx in bucket {
+                let worker = &self.workers[idx];
+                if worker.state == WorkerState::Dead || worker.state == WorkerState::Draining {
+                    continue;
+                }
+                if worker.in_backoff(now) {
+                    saw_backoff = true;
+                    continue;
+                }
+                return (Some(idx), saw_backoff);
+            }
+        }
+        (None, saw_backoff)
+    }
+
+    // Highest-capacity worker that is not `Dead`/`Draining` and not in
+    // backoff, regardless of whether it still has spare capacity. Used for
+    // the read-only peek in `get_least_busy_worker`.
+    fn least_busy(&self, now: u64) -> Option<usize> {
+        if self.paused {
+            return None;
+        }
+        for bucket in self.capacity_index.values().rev() {
+            for &idx in bucket {
+                let worker = &self.workers[idx];
+                if worker.state != WorkerState::Dead
+                    && worker.state != WorkerState::Draining
+                    && !worker.in_backoff(now)
+                {
+                    return Some(idx);
+                }
+            }
+        }
+        None
+    }
+}
+
+struct FunctionWorkerMapInner {
+    map: BTreeMap<StaticName, FunctionWorkerSlots>,
+    tranquilizers: BTreeMap<StaticName, Tranquilizer>,
+}
+
+// Cheaply cloneable handle around the shared routing table. The Arc<Mutex<_>>
+// is what lets a `WorkerLease` release its slot on Drop without borrowing the
+// map that handed it out.
+#[derive(Clone)]
 struct FunctionWorkerMap {
-    map: BTreeMap<StaticName, Vec<FunctionWorkerConfig>>,
+    inner: Arc<Mutex<FunctionWorkerMapInner>>,
 }
 
+#[derive(Debug)]
 struct LeastBusyWorkerError {
     error_code: u32,
     error_message: String,
 }
 
+impl LeastBusyWorkerError {
+    fn no_worker_mapped() -> LeastBusyWorkerError {
+        LeastBusyWorkerError {
+            error_code: 1,
+            error_message: "No worker is mapped for the function".to_string(),
+        }
+    }
+
+    fn all_workers_saturated() -> LeastBusyWorkerError {
+        LeastBusyWorkerError {
+            error_code: 3,
+            error_message:
+                "All workers for the function are at max_concurrency, dead, or draining, or the function is paused"
+                    .to_string(),
+        }
+    }
+
+    fn all_workers_in_backoff() -> LeastBusyWorkerError {
+        LeastBusyWorkerError {
+            error_code: 4,
+            error_message: "All workers for the function are quarantined in backoff".to_string(),
+        }
+    }
+}
+
+// A held slot on a worker, acquired via `FunctionWorkerMap::acquire_worker`.
+// Dropping it (or calling `release` explicitly) decrements the worker's
+// `traffic` back down, so a lease can never outlive the slot it reserved.
+struct WorkerLease {
+    worker_uuid: StaticName,
+    function_name: StaticName,
+    inner: Arc<Mutex<FunctionWorkerMapInner>>,
+    released: bool,
+    acquired_at_ms: u64,
+}
+
+impl WorkerLease {
+    pub fn worker_uuid(&self) -> StaticName {
+        self.worker_uuid
+    }
+
+    pub fn release(mut self) {
+        self.release_slot();
+    }
+
+    fn release_slot(&mut self) {
+        if self.released {
+            return;
+        }
+        self.released = true;
+
+        let now = now_ms();
+        let mut inner = self.inner.lock().unwrap();
+        if let Some(slots) = inner.map.get_mut(&self.function_name) {
+            if let Some(idx) = slots
+                .workers
+                .iter()
+                .position(|w| w.worker_uuid == self.worker_uuid)
+            {
+                let old_capacity = slots.workers[idx].available_capacity();
+                slots.workers[idx].traffic = slots.workers[idx].traffic.saturating_sub(1);
+                slots.reindex_capacity(idx, old_capacity);
+                if slots.workers[idx].pending_removal && slots.workers[idx].traffic == 0 {
+                    slots.remove_at(idx);
+                }
+            }
+        }
+
+        let elapsed_ms = now.saturating_sub(self.acquired_at_ms);
+        inner
+            .tranquilizers
+            .entry(self.function_name)
+            .or_insert_with(Tranquilizer::new)
+            .record_completion(now, elapsed_ms);
+    }
+}
+
+impl Drop for WorkerLease {
+    fn drop(&mut self) {
+        self.release_slot();
+    }
+}
+
 impl FunctionWorkerMap {
     pub fn new() -> FunctionWorkerMap {
         FunctionWorkerMap {
-            map: BTreeMap::new(),
+            inner: Arc::new(Mutex::new(FunctionWorkerMapInner {
+                map: BTreeMap::new(),
+                tranquilizers: BTreeMap::new(),
+            })),
         }
     }
 
@@ -80,60 +427,369 @@ impl FunctionWorkerMap {
         function_name: StaticName,
         worker_config: FunctionWorkerConfig,
     ) {
-        let worker_config_vec_option = self.map.get_mut(&function_name);
-        // Check if the worker config Array already exists
-        if worker_config_vec_option.is_none() {
-            let mut worker_config_vec: Vec<FunctionWorkerConfig> = Vec::new();
-            worker_config_vec.push(worker_config);
-            self.map.insert(function_name, worker_config_vec);
-        } else {
-            let worker_config_vec = worker_config_vec_option.unwrap();
-            worker_config_vec.push(worker_config);
-
-            // Decend Sort the worker configs based on the traffic `max_concurrency - traffic`
-            worker_config_vec.sort_by(|a, b| {
-                let a_traffic = a.traffic;
-                let b_traffic = b.traffic;
-                let a_max_concurrency = a.max_concurrency;
-                let b_max_concurrency = b.max_concurrency;
-                let a_traffic_ratio = (a_max_concurrency - a_traffic) as i32;
-                let b_traffic_ratio = (b_max_concurrency - b_traffic) as i32;
-                b_traffic_ratio.cmp(&a_traffic_ratio)
-            });
-        }
+        let mut inner = self.inner.lock().unwrap();
+        inner
+            .map
+            .entry(function_name)
+            .or_insert_with(FunctionWorkerSlots::new)
+            .push(worker_config);
     }
 
+    // Read-only peek at the least busy worker. Does not reserve a slot; use
+    // `acquire_worker` to actually route a call.
     pub fn get_least_busy_worker(
-        &mut self,
+        &self,
         function_name: StaticName,
     ) -> Result<FunctionWorkerConfig, LeastBusyWorkerError> {
-        let worker_config_vec_option = self.map.get(&function_name);
-        // check if any worker is mapped for the function
-        if worker_config_vec_option.is_none() {
-            return Result::Err(LeastBusyWorkerError {
-                error_code: 1,
-                error_message: "No worker is mapped for the function".to_string(),
-            });
+        let inner = self.inner.lock().unwrap();
+        let slots = inner
+            .map
+            .get(&function_name)
+            .ok_or_else(LeastBusyWorkerError::no_worker_mapped)?;
+
+        let idx = slots
+            .least_busy(now_ms())
+            .ok_or_else(LeastBusyWorkerError::no_worker_mapped)?;
+        Ok(slots.workers[idx].copy())
+    }
+
+    // Atomically reserves a slot on the least busy worker that still has
+    // spare capacity and returns a `WorkerLease` for it. Workers already at
+    // `max_concurrency`, marked `Dead`, or quarantined in backoff are skipped
+    // entirely rather than handed out anyway. If the function's tranquilizer
+    // has a pending pause from the previous completed lease, this waits it
+    // out before reserving a slot.
+    pub async fn acquire_worker(
+        &self,
+        function_name: StaticName,
+    ) -> Result<WorkerLease, LeastBusyWorkerError> {
+        loop {
+            let wait_ms = {
+                let inner = self.inner.lock().unwrap();
+                let ready_at_ms = inner
+                    .tranquilizers
+                    .get(&function_name)
+                    .map_or(0, |t| t.ready_at_ms);
+                ready_at_ms.saturating_sub(now_ms())
+            };
+            if wait_ms == 0 {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(wait_ms)).await;
+        }
+
+        let mut inner = self.inner.lock().unwrap();
+        let slots = inner
+            .map
+            .get_mut(&function_name)
+            .ok_or_else(LeastBusyWorkerError::no_worker_mapped)?;
+
+        if slots.workers.is_empty() {
+            return Result::Err(LeastBusyWorkerError::no_worker_mapped());
+        }
+
+        let now = now_ms();
+        let (best_idx, saw_backoff) = slots.best_available(now);
+        let idx = match best_idx {
+            Some(idx) => idx,
+            None if saw_backoff => return Err(LeastBusyWorkerError::all_workers_in_backoff()),
+            None => return Err(LeastBusyWorkerError::all_workers_saturated()),
+        };
+
+        let old_capacity = slots.workers[idx].available_capacity();
+        let worker = &mut slots.workers[idx];
+        worker.traffic = worker.traffic.saturating_add(1);
+        worker.state = WorkerState::Active;
+        let worker_uuid = worker.worker_uuid;
+        slots.reindex_capacity(idx, old_capacity);
+
+        Ok(WorkerLease {
+            worker_uuid,
+            function_name,
+            inner: Arc::clone(&self.inner),
+            released: false,
+            acquired_at_ms: now_ms(),
+        })
+    }
+
+    // Sets the admission-smoothing factor for a function: after each
+    // completed lease, with `d` the mean duration in ms of the function's
+    // recent completed leases, the next `acquire_worker` call pauses
+    // `d * tranquility / 100` ms. `tranquility = 0` disables the pause.
+    pub fn set_tranquility(&self, function_name: StaticName, tranquility: u32) {
+        let mut inner = self.inner.lock().unwrap();
+        inner
+            .tranquilizers
+            .entry(function_name)
+            .or_insert_with(Tranquilizer::new)
+            .tranquility = tranquility;
+    }
+
+    // Refreshes a worker's heartbeat timestamp and marks it `Active`/`Idle`
+    // depending on whether it currently has traffic. A `Draining` worker's
+    // administrative state is left untouched so a stray heartbeat can't
+    // revert it back to selectable. `worker_uuid` is unique across the whole
+    // map, so every function's worker list is searched.
+    pub fn record_heartbeat(&self, worker_uuid: StaticName) {
+        let mut inner = self.inner.lock().unwrap();
+        let now = now_ms();
+        for slots in inner.map.values_mut() {
+            if let Some(worker) = slots
+                .workers
+                .iter_mut()
+                .find(|w| w.worker_uuid == worker_uuid)
+            {
+                worker.last_heartbeat_ms = now;
+                if worker.state != WorkerState::Draining {
+                    worker.state = if worker.traffic > 0 {
+                        WorkerState::Active
+                    } else {
+                        WorkerState::Idle
+                    };
+                }
+                return;
+            }
+        }
+    }
+
+    // Reaper pass: flips any worker whose last heartbeat is older than
+    // `ttl_ms` (relative to `now`) to `Dead`, so it stops being selected.
+    pub fn mark_dead_workers(&self, now: u64, ttl_ms: u64) {
+        let mut inner = self.inner.lock().unwrap();
+        for slots in inner.map.values_mut() {
+            for worker in slots.workers.iter_mut() {
+                if now.saturating_sub(worker.last_heartbeat_ms) > ttl_ms {
+                    worker.state = WorkerState::Dead;
+                }
+            }
+        }
+    }
+
+    // Lists every worker registered for a function so an operator can see
+    // which ones are active, idle, or dead.
+    pub fn list_workers(&self, function_name: StaticName) -> Vec<WorkerInfo> {
+        let inner = self.inner.lock().unwrap();
+        match inner.map.get(&function_name) {
+            Some(slots) => slots
+                .workers
+                .iter()
+                .map(|w| WorkerInfo {
+                    worker_uuid: w.worker_uuid,
+                    state: w.state,
+                    traffic: w.traffic,
+                    max_concurrency: w.max_concurrency,
+                })
+                .collect(),
+            None => Vec::new(),
+        }
+    }
+
+    // Records a failed call to `worker_uuid`: bumps `error_count` and pushes
+    // `next_try_at` out by an exponentially growing backoff window, so a
+    // repeatedly-failing worker is temporarily skipped by selection.
+    pub fn report_failure(&self, worker_uuid: StaticName, now: u64) {
+        let mut inner = self.inner.lock().unwrap();
+        for slots in inner.map.values_mut() {
+            if let Some(worker) = slots
+                .workers
+                .iter_mut()
+                .find(|w| w.worker_uuid == worker_uuid)
+            {
+                worker.error_count = worker.error_count.saturating_add(1);
+                worker.last_error_at = now;
+                let backoff_ms = BASE_BACKOFF_MS
+                    .saturating_mul(1u64 << (worker.error_count - 1).min(32))
+                    .min(MAX_BACKOFF_MS);
+                worker.next_try_at = now + backoff_ms;
+                return;
+            }
+        }
+    }
+
+    // Clears a worker's backoff state after a successful call.
+    pub fn report_success(&self, worker_uuid: StaticName) {
+        let mut inner = self.inner.lock().unwrap();
+        for slots in inner.map.values_mut() {
+            if let Some(worker) = slots
+                .workers
+                .iter_mut()
+                .find(|w| w.worker_uuid == worker_uuid)
+            {
+                worker.error_count = 0;
+                worker.next_try_at = 0;
+                return;
+            }
+        }
+    }
+
+    // Stops issuing new leases to `worker_uuid` while letting whatever
+    // leases it already holds finish normally. Used ahead of a deploy or
+    // node maintenance, before the worker is eventually removed.
+    pub fn drain_worker(&self, worker_uuid: StaticName) {
+        let mut inner = self.inner.lock().unwrap();
+        for slots in inner.map.values_mut() {
+            if let Some(worker) = slots
+                .workers
+                .iter_mut()
+                .find(|w| w.worker_uuid == worker_uuid)
+            {
+                worker.state = WorkerState::Draining;
+                return;
+            }
+        }
+    }
+
+    // Stops issuing new leases for `function_name` entirely, without
+    // disturbing leases already in flight.
+    pub fn pause_function(&self, function_name: StaticName) {
+        let mut inner = self.inner.lock().unwrap();
+        if let Some(slots) = inner.map.get_mut(&function_name) {
+            slots.paused = true;
+        }
+    }
+
+    // Reverses `pause_function`, allowing new leases again.
+    pub fn resume_function(&self, function_name: StaticName) {
+        let mut inner = self.inner.lock().unwrap();
+        if let Some(slots) = inner.map.get_mut(&function_name) {
+            slots.paused = false;
+        }
+    }
+
+    // Unregisters `worker_uuid`. If it's currently idle (`traffic == 0`) it's
+    // removed immediately; otherwise it's marked `Draining` (excluding it
+    // from new selection) and removal is deferred, completing as soon as
+    // its last in-flight lease is released.
+    pub fn remove_worker(&self, worker_uuid: StaticName) {
+        let mut inner = self.inner.lock().unwrap();
+        for slots in inner.map.values_mut() {
+            if let Some(idx) = slots
+                .workers
+                .iter()
+                .position(|w| w.worker_uuid == worker_uuid)
+            {
+                if slots.workers[idx].traffic == 0 {
+                    slots.remove_at(idx);
+                } else {
+                    slots.workers[idx].pending_removal = true;
+                    slots.workers[idx].state = WorkerState::Draining;
+                }
+                return;
+            }
+        }
+    }
+
+    // Spawns a background task that applies `RouterCommand`s as they arrive,
+    // so the router can be reconfigured live (e.g. from a deploy script)
+    // without the caller blocking on each change.
+    pub fn spawn_command_loop(&self, mut commands: mpsc::Receiver<RouterCommand>) {
+        let map = self.clone();
+        tokio::spawn(async move {
+            while let Some(command) = commands.recv().await {
+                map.apply_command(command);
+            }
+        });
+    }
+
+    fn apply_command(&self, command: RouterCommand) {
+        match command {
+            RouterCommand::DrainWorker(worker_uuid) => self.drain_worker(worker_uuid),
+            RouterCommand::PauseFunction(function_name) => self.pause_function(function_name),
+            RouterCommand::ResumeFunction(function_name) => self.resume_function(function_name),
+            RouterCommand::RemoveWorker(worker_uuid) => self.remove_worker(worker_uuid),
         }
+    }
+
+    // Structured routing-pressure report: a per-function rollup plus a flat
+    // list of per-worker gauges, suitable for feeding a Prometheus-style
+    // exporter.
+    pub fn metrics_snapshot(&self) -> MetricsSnapshot {
+        let inner = self.inner.lock().unwrap();
+        let now = now_ms();
 
-        let worker_config_vec = worker_config_vec_option.unwrap();
+        let mut functions = Vec::new();
+        let mut workers = Vec::new();
 
-        if worker_config_vec.len() == 0 {
-            return Result::Err(LeastBusyWorkerError {
-                error_code: 2,
-                error_message: "No worker is mapped for the function".to_string(),
+        for (function_name, slots) in inner.map.iter() {
+            let mut total_traffic: u32 = 0;
+            let mut total_max_concurrency: u32 = 0;
+            let mut saturated_count: usize = 0;
+            let mut dead_count: usize = 0;
+            let mut backing_off_count: usize = 0;
+
+            for worker in &slots.workers {
+                total_traffic += worker.traffic;
+                total_max_concurrency += worker.max_concurrency;
+                if worker.available_capacity() <= 0 {
+                    saturated_count += 1;
+                }
+                if worker.state == WorkerState::Dead {
+                    dead_count += 1;
+                }
+                if worker.in_backoff(now) {
+                    backing_off_count += 1;
+                }
+
+                workers.push(WorkerGauge {
+                    function_name: *function_name,
+                    worker_uuid: worker.worker_uuid,
+                    traffic: worker.traffic,
+                    max_concurrency: worker.max_concurrency,
+                    state: worker.state,
+                });
+            }
+
+            functions.push(FunctionMetrics {
+                function_name: *function_name,
+                worker_count: slots.workers.len(),
+                total_traffic,
+                total_max_concurrency,
+                available_capacity: total_max_concurrency as i64 - total_traffic as i64,
+                saturated_count,
+                dead_count,
+                backing_off_count,
             });
         }
 
-        // return the least busy worker
-        let least_busy_worker = worker_config_vec[0].copy();
-        Ok(least_busy_worker)
+        MetricsSnapshot { functions, workers }
     }
 }
 
+// Administrative operations that can be sent over an `mpsc` channel to
+// `FunctionWorkerMap::spawn_command_loop` for live reconfiguration during
+// deploys or node maintenance, without dropping in-flight executions.
+pub enum RouterCommand {
+    DrainWorker(StaticName),
+    PauseFunction(StaticName),
+    ResumeFunction(StaticName),
+    RemoveWorker(StaticName),
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+
+    fn sample_worker_config(
+        function_name: StaticName,
+        worker_uuid: StaticName,
+        traffic: u32,
+        max_concurrency: u32,
+    ) -> FunctionWorkerConfig {
+        FunctionWorkerConfig {
+            function_name,
+            worker_uuid,
+            timeout: 10000,
+            traffic,
+            max_concurrency,
+            state: WorkerState::Idle,
+            last_heartbeat_ms: now_ms(),
+            error_count: 0,
+            last_error_at: 0,
+            next_try_at: 0,
+            pending_removal: false,
+        }
+    }
+
     #[test]
     /// - Create a FunctionWorkerMap
     /// - Insert 1st worker config example, using insert_worker_config
@@ -154,18 +810,25 @@ mod tests {
             timeout: timeout,
             traffic: traffic,
             max_concurrency: max_concurrency,
+            state: WorkerState::Idle,
+            last_heartbeat_ms: now_ms(),
+            error_count: 0,
+            last_error_at: 0,
+            next_try_at: 0,
+            pending_removal: false,
         };
 
         function_worker_map.insert_worker_config(function_name, worker_config);
-        let worker_config_vec_option = function_worker_map.map.get(&function_name);
-        assert_eq!(worker_config_vec_option.is_some(), true);
-        let worker_config_vec = worker_config_vec_option.unwrap();
-        assert_eq!(worker_config_vec.len(), 1);
-        assert_eq!(worker_config_vec[0].function_name, function_name);
-        assert_eq!(worker_config_vec[0].worker_uuid, worker_uuid);
-        assert_eq!(worker_config_vec[0].timeout, timeout);
-        assert_eq!(worker_config_vec[0].traffic, traffic);
-        assert_eq!(worker_config_vec[0].max_concurrency, max_concurrency);
+        let inner = function_worker_map.inner.lock().unwrap();
+        let slots_option = inner.map.get(&function_name);
+        assert_eq!(slots_option.is_some(), true);
+        let slots = slots_option.unwrap();
+        assert_eq!(slots.workers.len(), 1);
+        assert_eq!(slots.workers[0].function_name, function_name);
+        assert_eq!(slots.workers[0].worker_uuid, worker_uuid);
+        assert_eq!(slots.workers[0].timeout, timeout);
+        assert_eq!(slots.workers[0].traffic, traffic);
+        assert_eq!(slots.workers[0].max_concurrency, max_concurrency);
     }
 
     #[test]
@@ -191,6 +854,12 @@ mod tests {
             timeout: timeout1,
             traffic: traffic1,
             max_concurrency: max_concurrency1,
+            state: WorkerState::Idle,
+            last_heartbeat_ms: now_ms(),
+            error_count: 0,
+            last_error_at: 0,
+            next_try_at: 0,
+            pending_removal: false,
         };
 
         function_worker_map.insert_worker_config(function_name1, worker_config);
@@ -207,6 +876,12 @@ mod tests {
             timeout: timeout,
             traffic: traffic,
             max_concurrency: max_concurrency,
+            state: WorkerState::Idle,
+            last_heartbeat_ms: now_ms(),
+            error_count: 0,
+            last_error_at: 0,
+            next_try_at: 0,
+            pending_removal: false,
         };
 
         function_worker_map.insert_worker_config(function_name, worker_config);
@@ -223,22 +898,398 @@ mod tests {
             timeout: timeout3,
             traffic: traffic3,
             max_concurrency: max_concurrency3,
+            state: WorkerState::Idle,
+            last_heartbeat_ms: now_ms(),
+            error_count: 0,
+            last_error_at: 0,
+            next_try_at: 0,
+            pending_removal: false,
         };
 
         function_worker_map.insert_worker_config(function_name3, worker_config);
 
-        let worker_config_vec_option = function_worker_map.map.get(&function_name3);
-
-        assert_eq!(worker_config_vec_option.is_some(), true);
-        let worker_config_vec = worker_config_vec_option.unwrap();
-        assert_eq!(worker_config_vec.len(), 2);
-        assert_eq!(worker_config_vec[0].function_name, function_name3);
-        assert_eq!(worker_config_vec[0].worker_uuid, worker_uuid3);
-        // assert_eq!(worker_config_vec[0].timeout, timeout);
-        // assert_eq!(worker_config_vec[0].traffic, traffic);
-        
-        assert_eq!(worker_config_vec[1].function_name, function_name1);
-        assert_eq!(worker_config_vec[1].worker_uuid, worker_uuid1);
-        // assert_eq!(worker_config_vec[1].timeout, timeout1);
+        let inner = function_worker_map.inner.lock().unwrap();
+        let slots_option = inner.map.get(&function_name3);
+
+        assert_eq!(slots_option.is_some(), true);
+        let slots = slots_option.unwrap();
+        assert_eq!(slots.workers.len(), 2);
+        assert_eq!(slots.workers[0].function_name, function_name1);
+        assert_eq!(slots.workers[0].worker_uuid, worker_uuid1);
+
+        assert_eq!(slots.workers[1].function_name, function_name3);
+        assert_eq!(slots.workers[1].worker_uuid, worker_uuid3);
+    }
+
+    #[tokio::test]
+    /// - Acquire a worker, assert traffic is incremented on the live map
+    /// - Drop the lease, assert traffic is decremented back to zero
+    async fn test_acquire_worker_increments_and_release_decrements_traffic() {
+        let mut function_worker_map = FunctionWorkerMap::new();
+        let function_name = static_name_from_str("test-function1");
+        let worker_uuid = static_name_from_str("test-worker1");
+
+        function_worker_map.insert_worker_config(
+            function_name,
+            sample_worker_config(function_name, worker_uuid, 0, 2),
+        );
+
+        let lease = function_worker_map.acquire_worker(function_name).await.unwrap();
+        assert_eq!(lease.worker_uuid(), worker_uuid);
+        {
+            let inner = function_worker_map.inner.lock().unwrap();
+            assert_eq!(inner.map.get(&function_name).unwrap().workers[0].traffic, 1);
+        }
+
+        drop(lease);
+        let inner = function_worker_map.inner.lock().unwrap();
+        assert_eq!(inner.map.get(&function_name).unwrap().workers[0].traffic, 0);
+    }
+
+    #[tokio::test]
+    /// - Two workers: one already saturated, one with spare capacity
+    /// - Assert acquire_worker always picks the worker with spare capacity
+    async fn test_acquire_worker_skips_saturated_workers() {
+        let mut function_worker_map = FunctionWorkerMap::new();
+        let function_name = static_name_from_str("test-function1");
+        let saturated_worker = static_name_from_str("test-worker-saturated");
+        let free_worker = static_name_from_str("test-worker-free");
+
+        function_worker_map.insert_worker_config(
+            function_name,
+            sample_worker_config(function_name, saturated_worker, 1, 1),
+        );
+        function_worker_map.insert_worker_config(
+            function_name,
+            sample_worker_config(function_name, free_worker, 0, 1),
+        );
+
+        let lease = function_worker_map.acquire_worker(function_name).await.unwrap();
+        assert_eq!(lease.worker_uuid(), free_worker);
+    }
+
+    #[tokio::test]
+    /// - A single worker already at max_concurrency
+    /// - Assert acquire_worker returns the "all workers saturated" error
+    async fn test_acquire_worker_all_saturated_errors() {
+        let mut function_worker_map = FunctionWorkerMap::new();
+        let function_name = static_name_from_str("test-function1");
+        let worker_uuid = static_name_from_str("test-worker1");
+
+        function_worker_map.insert_worker_config(
+            function_name,
+            sample_worker_config(function_name, worker_uuid, 1, 1),
+        );
+
+        let result = function_worker_map.acquire_worker(function_name).await;
+        assert_eq!(result.err().unwrap().error_code, 3);
+    }
+
+    #[test]
+    /// - Insert a worker, record a heartbeat while it has traffic
+    /// - Assert it is marked Active, then Idle once traffic drops
+    fn test_record_heartbeat_tracks_active_and_idle() {
+        let mut function_worker_map = FunctionWorkerMap::new();
+        let function_name = static_name_from_str("test-function1");
+        let worker_uuid = static_name_from_str("test-worker1");
+
+        function_worker_map.insert_worker_config(
+            function_name,
+            sample_worker_config(function_name, worker_uuid, 1, 2),
+        );
+
+        function_worker_map.record_heartbeat(worker_uuid);
+        let workers = function_worker_map.list_workers(function_name);
+        assert_eq!(workers[0].state, WorkerState::Active);
+
+        {
+            let mut inner = function_worker_map.inner.lock().unwrap();
+            inner.map.get_mut(&function_name).unwrap().workers[0].traffic = 0;
+        }
+        function_worker_map.record_heartbeat(worker_uuid);
+        let workers = function_worker_map.list_workers(function_name);
+        assert_eq!(workers[0].state, WorkerState::Idle);
+    }
+
+    #[tokio::test]
+    /// - Insert a worker whose heartbeat is older than the TTL
+    /// - Assert mark_dead_workers flips it to Dead and acquire_worker skips it
+    async fn test_mark_dead_workers_excludes_from_selection() {
+        let mut function_worker_map = FunctionWorkerMap::new();
+        let function_name = static_name_from_str("test-function1");
+        let worker_uuid = static_name_from_str("test-worker1");
+
+        function_worker_map.insert_worker_config(
+            function_name,
+            sample_worker_config(function_name, worker_uuid, 0, 1),
+        );
+
+        let now = now_ms();
+        function_worker_map.mark_dead_workers(now + 100_000, 1_000);
+
+        let workers = function_worker_map.list_workers(function_name);
+        assert_eq!(workers[0].state, WorkerState::Dead);
+
+        let result = function_worker_map.acquire_worker(function_name).await;
+        assert_eq!(result.err().unwrap().error_code, 3);
+    }
+
+    #[tokio::test]
+    /// - Report a failure for a worker, assert it is quarantined from selection
+    /// - Report a success, assert it becomes selectable again
+    async fn test_report_failure_quarantines_then_report_success_clears() {
+        let mut function_worker_map = FunctionWorkerMap::new();
+        let function_name = static_name_from_str("test-function1");
+        let worker_uuid = static_name_from_str("test-worker1");
+
+        function_worker_map.insert_worker_config(
+            function_name,
+            sample_worker_config(function_name, worker_uuid, 0, 1),
+        );
+
+        let now = now_ms();
+        function_worker_map.report_failure(worker_uuid, now);
+
+        let result = function_worker_map.acquire_worker(function_name).await;
+        assert_eq!(result.err().unwrap().error_code, 4);
+
+        function_worker_map.report_success(worker_uuid);
+        let lease = function_worker_map.acquire_worker(function_name).await.unwrap();
+        assert_eq!(lease.worker_uuid(), worker_uuid);
+    }
+
+    #[test]
+    /// - Report repeated failures, assert the backoff window grows but is capped
+    fn test_report_failure_backoff_doubles_and_caps() {
+        let mut function_worker_map = FunctionWorkerMap::new();
+        let function_name = static_name_from_str("test-function1");
+        let worker_uuid = static_name_from_str("test-worker1");
+
+        function_worker_map.insert_worker_config(
+            function_name,
+            sample_worker_config(function_name, worker_uuid, 0, 1),
+        );
+
+        let now = 0u64;
+        function_worker_map.report_failure(worker_uuid, now);
+        {
+            let inner = function_worker_map.inner.lock().unwrap();
+            assert_eq!(
+                inner.map.get(&function_name).unwrap().workers[0].next_try_at,
+                BASE_BACKOFF_MS
+            );
+        }
+
+        for _ in 0..10 {
+            function_worker_map.report_failure(worker_uuid, now);
+        }
+        let inner = function_worker_map.inner.lock().unwrap();
+        assert_eq!(
+            inner.map.get(&function_name).unwrap().workers[0].next_try_at,
+            MAX_BACKOFF_MS
+        );
+    }
+
+    #[test]
+    /// - Record a lease completion directly on a function's tranquilizer
+    /// - Assert the pause it schedules is proportional to the tranquility factor
+    fn test_tranquilizer_schedules_proportional_pause() {
+        let function_worker_map = FunctionWorkerMap::new();
+        let function_name = static_name_from_str("test-function1");
+
+        function_worker_map.set_tranquility(function_name, 50);
+
+        let mut inner = function_worker_map.inner.lock().unwrap();
+        let tranquilizer = inner.tranquilizers.get_mut(&function_name).unwrap();
+        tranquilizer.record_completion(1_000, 200);
+        assert_eq!(tranquilizer.ready_at_ms, 1_100); // 200ms * 50 / 100 = 100ms pause
+    }
+
+    #[test]
+    /// - A tranquility of 0 (the default) must never schedule a pause
+    fn test_tranquilizer_disabled_by_default() {
+        let function_worker_map = FunctionWorkerMap::new();
+        let function_name = static_name_from_str("test-function1");
+
+        let mut inner = function_worker_map.inner.lock().unwrap();
+        let tranquilizer = inner
+            .tranquilizers
+            .entry(function_name)
+            .or_insert_with(Tranquilizer::new);
+        tranquilizer.record_completion(1_000, 200);
+        assert_eq!(tranquilizer.ready_at_ms, 0);
+    }
+
+    #[test]
+    /// - Two workers for the same function, one saturated; a reaper pass far
+    ///   past both heartbeats marks both of them dead
+    /// - Assert metrics_snapshot rolls up totals and per-worker gauges correctly
+    fn test_metrics_snapshot_rolls_up_function_and_worker_gauges() {
+        let mut function_worker_map = FunctionWorkerMap::new();
+        let function_name = static_name_from_str("test-function1");
+        let saturated_worker = static_name_from_str("test-worker-saturated");
+        let dead_worker = static_name_from_str("test-worker-dead");
+
+        function_worker_map.insert_worker_config(
+            function_name,
+            sample_worker_config(function_name, saturated_worker, 1, 1),
+        );
+        function_worker_map.insert_worker_config(
+            function_name,
+            sample_worker_config(function_name, dead_worker, 0, 2),
+        );
+        function_worker_map.mark_dead_workers(now_ms() + 100_000, 1_000);
+
+        let snapshot = function_worker_map.metrics_snapshot();
+        assert_eq!(snapshot.functions.len(), 1);
+        let function_metrics = &snapshot.functions[0];
+        assert_eq!(function_metrics.worker_count, 2);
+        assert_eq!(function_metrics.total_traffic, 1);
+        assert_eq!(function_metrics.total_max_concurrency, 3);
+        assert_eq!(function_metrics.available_capacity, 2);
+        assert_eq!(function_metrics.saturated_count, 1);
+        assert_eq!(function_metrics.dead_count, 2);
+
+        assert_eq!(snapshot.workers.len(), 2);
+    }
+
+    #[tokio::test]
+    /// - Drain a worker, assert it is skipped by acquire_worker
+    /// - Assert a lease it already held is unaffected and still releases normally
+    async fn test_drain_worker_excludes_from_selection_but_keeps_existing_lease() {
+        let mut function_worker_map = FunctionWorkerMap::new();
+        let function_name = static_name_from_str("test-function1");
+        let worker_uuid = static_name_from_str("test-worker1");
+
+        function_worker_map.insert_worker_config(
+            function_name,
+            sample_worker_config(function_name, worker_uuid, 0, 2),
+        );
+
+        let lease = function_worker_map.acquire_worker(function_name).await.unwrap();
+        function_worker_map.drain_worker(worker_uuid);
+
+        let result = function_worker_map.acquire_worker(function_name).await;
+        assert_eq!(result.err().unwrap().error_code, 3);
+
+        drop(lease);
+        let workers = function_worker_map.list_workers(function_name);
+        assert_eq!(workers[0].traffic, 0);
+    }
+
+    #[tokio::test]
+    /// - Pause a function, assert acquire_worker fails even with idle workers
+    /// - Resume the function, assert acquire_worker succeeds again
+    async fn test_pause_and_resume_function() {
+        let mut function_worker_map = FunctionWorkerMap::new();
+        let function_name = static_name_from_str("test-function1");
+        let worker_uuid = static_name_from_str("test-worker1");
+
+        function_worker_map.insert_worker_config(
+            function_name,
+            sample_worker_config(function_name, worker_uuid, 0, 1),
+        );
+
+        function_worker_map.pause_function(function_name);
+        let result = function_worker_map.acquire_worker(function_name).await;
+        assert_eq!(result.err().unwrap().error_code, 3);
+
+        function_worker_map.resume_function(function_name);
+        let lease = function_worker_map.acquire_worker(function_name).await.unwrap();
+        assert_eq!(lease.worker_uuid(), worker_uuid);
+    }
+
+    #[test]
+    /// - Remove an idle worker, assert it disappears from list_workers immediately
+    fn test_remove_worker_idle_removes_immediately() {
+        let mut function_worker_map = FunctionWorkerMap::new();
+        let function_name = static_name_from_str("test-function1");
+        let worker_uuid = static_name_from_str("test-worker1");
+
+        function_worker_map.insert_worker_config(
+            function_name,
+            sample_worker_config(function_name, worker_uuid, 0, 1),
+        );
+
+        function_worker_map.remove_worker(worker_uuid);
+        let workers = function_worker_map.list_workers(function_name);
+        assert_eq!(workers.len(), 0);
+    }
+
+    #[tokio::test]
+    /// - Remove a worker with an in-flight lease, assert it is deferred
+    /// - Drop the lease, assert the worker is then actually removed
+    async fn test_remove_worker_busy_defers_until_traffic_drains() {
+        let mut function_worker_map = FunctionWorkerMap::new();
+        let function_name = static_name_from_str("test-function1");
+        let worker_uuid = static_name_from_str("test-worker1");
+
+        function_worker_map.insert_worker_config(
+            function_name,
+            sample_worker_config(function_name, worker_uuid, 0, 1),
+        );
+
+        let lease = function_worker_map.acquire_worker(function_name).await.unwrap();
+        function_worker_map.remove_worker(worker_uuid);
+
+        let workers = function_worker_map.list_workers(function_name);
+        assert_eq!(workers.len(), 1);
+
+        drop(lease);
+        let workers = function_worker_map.list_workers(function_name);
+        assert_eq!(workers.len(), 0);
+    }
+
+    #[tokio::test]
+    /// - Remove a worker with an in-flight lease so removal is deferred
+    /// - Drop the lease and assert the capacity index is left consistent:
+    ///   get_least_busy_worker and metrics_snapshot must not panic or
+    ///   reference the now-removed worker
+    async fn test_remove_worker_busy_leaves_capacity_index_consistent_after_release() {
+        let mut function_worker_map = FunctionWorkerMap::new();
+        let function_name = static_name_from_str("test-function1");
+        let worker_uuid = static_name_from_str("test-worker1");
+
+        function_worker_map.insert_worker_config(
+            function_name,
+            sample_worker_config(function_name, worker_uuid, 0, 1),
+        );
+
+        let lease = function_worker_map.acquire_worker(function_name).await.unwrap();
+        function_worker_map.remove_worker(worker_uuid);
+
+        drop(lease);
+
+        let result = function_worker_map.get_least_busy_worker(function_name);
+        assert_eq!(result.err().unwrap().error_code, 1);
+
+        let snapshot = function_worker_map.metrics_snapshot();
+        assert_eq!(snapshot.workers.len(), 0);
+    }
+
+    #[tokio::test]
+    /// - Send a PauseFunction command over an mpsc channel
+    /// - Assert the command loop applies them without the caller blocking
+    async fn test_spawn_command_loop_applies_commands() {
+        let mut function_worker_map = FunctionWorkerMap::new();
+        let function_name = static_name_from_str("test-function1");
+        let worker_uuid = static_name_from_str("test-worker1");
+
+        function_worker_map.insert_worker_config(
+            function_name,
+            sample_worker_config(function_name, worker_uuid, 0, 1),
+        );
+
+        let (tx, rx) = mpsc::channel(4);
+        function_worker_map.spawn_command_loop(rx);
+
+        tx.send(RouterCommand::PauseFunction(function_name))
+            .await
+            .unwrap();
+        drop(tx);
+        tokio::time::sleep(Duration::from_millis(10)).await;
+
+        let result = function_worker_map.acquire_worker(function_name).await;
+        assert_eq!(result.err().unwrap().error_code, 3);
     }
 }